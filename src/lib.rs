@@ -22,8 +22,47 @@
 //! buffer.push_str( "I like cupcakes!" );
 //! memory_pool::release( buffer );
 //! ```
+//!
+//! If you don't want to worry about releasing the buffer yourself you can acquire
+//! a guard which will release it automatically once it's dropped:
+//!
+//! ```rust
+//! {
+//!     let mut buffer = memory_pool::acquire_guard::< String >();
+//!     buffer.push_str( "I like cupcakes!" );
+//! }
+//! ```
+//!
+//! If you know upfront roughly how big of a buffer you're going to need you can ask
+//! for one with at least a given capacity, which avoids getting back a tiny buffer
+//! that would just have to be reallocated:
+//!
+//! ```rust
+//! let mut buffer: String = memory_pool::acquire_with_capacity( 4096 );
+//! buffer.push_str( "I like cupcakes!" );
+//! memory_pool::release( buffer );
+//! ```
+//!
+//! If you have a lot of short-lived, mixed-type temporaries you'd rather not allocate
+//! (and free) one by one, you can stash them in a `Scope` and let it drop them all at
+//! once once you're done:
+//!
+//! ```rust
+//! use memory_pool::Scope;
+//!
+//! let scope = Scope::new();
+//! let aux: &mut String = scope.keep( String::new() );
+//! aux.push_str( "I like cupcakes!" );
+//! ```
 
 mod memory_pool;
 mod poolable;
+mod scope;
 
-pub use memory_pool::{acquire, release, borrow};
+pub use memory_pool::{
+    acquire, release, borrow, acquire_guard, Reusable,
+    acquire_with_capacity, borrow_with_capacity,
+    configure_global,
+    set_limits, stats, Stats
+};
+pub use scope::Scope;
@@ -1,26 +1,269 @@
 use std::cell::UnsafeCell;
 use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
 
 use poolable::Poolable;
 
 thread_local!( static POOL: UnsafeCell< MemoryPool > = UnsafeCell::new( MemoryPool::new() ) );
 
+/// The upper bound (in bytes) of each size-class bucket `buffers` is partitioned into.
+/// A buffer is stored in the first bucket whose bound is `>= capacity`; anything bigger
+/// than the last bound ends up in an unbounded catch-all bucket.
+const SIZE_CLASSES: [usize; 7] = [ 64, 256, 1024, 4096, 16384, 65536, 262144 ];
+
+/// Once a thread-local pool is holding on to more buffers than this, it starts spilling
+/// its excess into the global pool so other threads can reuse them.
+const LOCAL_HIGH_WATERMARK: usize = 64;
+
+/// How many buffers get moved between a thread-local pool and the global pool at once,
+/// to keep global-lock contention low.
+const GLOBAL_BATCH_SIZE: usize = 16;
+
+/// A raw buffer pointer paired with its capacity. Only ever stored while no `MemoryPool`
+/// holds a live reference into its memory, so it's safe to move between threads.
+struct RawBuffer( *mut u8, usize );
+unsafe impl Send for RawBuffer {}
+
+struct GlobalPool {
+    buffers: Vec< RawBuffer >,
+    max_buffers: usize
+}
+
+impl GlobalPool {
+    fn push_batch( &mut self, batch: Vec< RawBuffer > ) {
+        for buffer in batch {
+            if self.buffers.len() >= self.max_buffers {
+                let RawBuffer( ptr, capacity ) = buffer;
+                let vector = unsafe { Vec::from_raw_parts( ptr, 0, capacity ) };
+                mem::drop( vector );
+            } else {
+                self.buffers.push( buffer );
+            }
+        }
+    }
+
+    fn pop_batch( &mut self, count: usize ) -> Vec< RawBuffer > {
+        let new_length = self.buffers.len().saturating_sub( count );
+        self.buffers.split_off( new_length )
+    }
+}
+
+static GLOBAL: Mutex< GlobalPool > = Mutex::new( GlobalPool { buffers: Vec::new(), max_buffers: 1024 } );
+
+/// Configures the maximum number of buffers the global, cross-thread pool is allowed to
+/// retain. Thread-local pools spill their excess buffers into the global pool (and draw
+/// from it once they run dry) instead of losing them once a thread exits.
+pub fn configure_global( max_buffers: usize ) {
+    GLOBAL.lock().unwrap().max_buffers = max_buffers;
+}
+
+/// A snapshot of a thread-local pool's usage, returned by [`stats`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stats {
+    /// How many times an `acquire`-family call was served from a pooled buffer.
+    pub hits: u64,
+    /// How many times an `acquire`-family call had to allocate a fresh buffer.
+    pub misses: u64,
+    /// How many buffers the pool is currently retaining.
+    pub buffer_count: usize,
+    /// The combined capacity (in bytes) of every buffer the pool is currently retaining.
+    pub total_bytes: usize
+}
+
 struct MemoryPool {
-    buffers: Vec< (*mut u8, usize) >
+    // One bucket per entry in `SIZE_CLASSES`, plus one extra catch-all bucket for
+    // buffers bigger than the biggest size class.
+    buffers: Vec< Vec< (*mut u8, usize) > >,
+    max_buffers: usize,
+    max_total_bytes: usize,
+    hits: u64,
+    misses: u64
 }
 
 impl MemoryPool {
     fn new() -> MemoryPool {
         MemoryPool {
-            buffers: Vec::new()
+            buffers: vec![ Vec::new(); SIZE_CLASSES.len() + 1 ],
+            max_buffers: usize::max_value(),
+            max_total_bytes: usize::max_value(),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    #[inline]
+    fn bucket_for( capacity: usize ) -> usize {
+        SIZE_CLASSES.iter().position( |&size| capacity <= size ).unwrap_or( SIZE_CLASSES.len() )
+    }
+
+    // Scans smallest-bucket-first, so plain, capacity-agnostic callers get the cheapest
+    // available buffer instead of always being handed whatever's sitting in the biggest
+    // (or catch-all) bucket.
+    fn acquire_local( &mut self ) -> Option< (*mut u8, usize) > {
+        for bucket in self.buffers.iter_mut() {
+            if let Some( item ) = bucket.pop() {
+                return Some( item );
+            }
+        }
+
+        None
+    }
+
+    fn acquire_local_with_capacity( &mut self, min_capacity: usize ) -> Option< (*mut u8, usize) > {
+        let start = Self::bucket_for( min_capacity );
+
+        // The starting bucket can contain buffers smaller than what we need (its lower
+        // bound isn't necessarily `>= min_capacity`), so it needs an actual best-fit scan.
+        let best = self.buffers[ start ].iter()
+            .enumerate()
+            .filter( |&(_, &(_, capacity))| capacity >= min_capacity )
+            .min_by_key( |&(_, &(_, capacity))| capacity )
+            .map( |(index, _)| index );
+
+        if let Some( index ) = best {
+            return Some( self.buffers[ start ].swap_remove( index ) );
+        }
+
+        // Every buffer in a higher bucket is by construction big enough, and every buffer
+        // in the first non-empty one is smaller than those in the buckets after it, so the
+        // smallest buffer of that bucket is the best fit.
+        for bucket in self.buffers[ start + 1.. ].iter_mut() {
+            let smallest = bucket.iter()
+                .enumerate()
+                .min_by_key( |&(_, &(_, capacity))| capacity )
+                .map( |(index, _)| index );
+
+            if let Some( index ) = smallest {
+                return Some( bucket.swap_remove( index ) );
+            }
+        }
+
+        None
+    }
+
+    fn total_len( &self ) -> usize {
+        self.buffers.iter().map( |bucket| bucket.len() ).sum()
+    }
+
+    fn total_bytes( &self ) -> usize {
+        self.buffers.iter().flat_map( |bucket| bucket.iter() ).map( |&(_, capacity)| capacity ).sum()
+    }
+
+    fn set_limits( &mut self, max_buffers: usize, max_total_bytes: usize ) {
+        self.max_buffers = max_buffers;
+        self.max_total_bytes = max_total_bytes;
+        self.enforce_limits();
+    }
+
+    fn stats( &self ) -> Stats {
+        Stats {
+            hits: self.hits,
+            misses: self.misses,
+            buffer_count: self.total_len(),
+            total_bytes: self.total_bytes()
+        }
+    }
+
+    // Frees the smallest buffer we're currently retaining so it stops counting towards
+    // our limits. Returns `false` if we aren't retaining anything at all.
+    fn evict_smallest( &mut self ) -> bool {
+        let smallest = self.buffers.iter()
+            .enumerate()
+            .flat_map( |(bucket_index, bucket)| bucket.iter().enumerate().map( move |(item_index, &(_, capacity))| (bucket_index, item_index, capacity) ) )
+            .min_by_key( |&(_, _, capacity)| capacity );
+
+        match smallest {
+            Some( (bucket_index, item_index, _) ) => {
+                let (ptr, capacity) = self.buffers[ bucket_index ].swap_remove( item_index );
+                let vector = unsafe { Vec::from_raw_parts( ptr, 0, capacity ) };
+                mem::drop( vector );
+
+                true
+            },
+            None => false
+        }
+    }
+
+    fn enforce_limits( &mut self ) {
+        while self.total_len() > self.max_buffers || self.total_bytes() > self.max_total_bytes {
+            if !self.evict_smallest() {
+                break;
+            }
+        }
+    }
+
+    fn spill_to_global_if_full( &mut self ) {
+        if self.total_len() <= LOCAL_HIGH_WATERMARK {
+            return;
+        }
+
+        let mut batch = Vec::with_capacity( GLOBAL_BATCH_SIZE );
+        for bucket in self.buffers.iter_mut().rev() {
+            while batch.len() < GLOBAL_BATCH_SIZE {
+                match bucket.pop() {
+                    Some( (ptr, capacity) ) => batch.push( RawBuffer( ptr, capacity ) ),
+                    None => break
+                }
+            }
+
+            if batch.len() >= GLOBAL_BATCH_SIZE {
+                break;
+            }
+        }
+
+        if !batch.is_empty() {
+            GLOBAL.lock().unwrap().push_batch( batch );
+        }
+    }
+
+    // Mirrors the spill side: pull at most a batch's worth rather than hoarding
+    // everything the global pool has, so a single thread's miss can't starve every
+    // other thread that's also drawing from the same global pool.
+    fn pull_from_global( &mut self ) {
+        let batch = GLOBAL.lock().unwrap().pop_batch( GLOBAL_BATCH_SIZE );
+        for RawBuffer( ptr, capacity ) in batch {
+            let bucket = Self::bucket_for( capacity );
+            self.buffers[ bucket ].push( (ptr, capacity) );
         }
     }
 
+    // Note: plain `acquire` intentionally only ever looks at the local pool (unlike
+    // `acquire_with_capacity` below) so it keeps returning a fresh, empty (unallocated)
+    // `T` whenever the local pool has nothing to offer, rather than an arbitrary
+    // buffer pulled in from some other thread.
     #[inline]
     fn acquire<T>( &mut self ) -> T where T: Poolable {
-        match self.buffers.pop() {
-            None => T::empty(),
-            Some( (ptr, capacity) ) => unsafe { T::from_buffer( ptr, capacity ) }
+        match self.acquire_local() {
+            Some( (ptr, capacity) ) => {
+                self.hits += 1;
+                unsafe { T::from_buffer( ptr, capacity ) }
+            },
+            None => {
+                self.misses += 1;
+                T::empty()
+            }
+        }
+    }
+
+    #[inline]
+    fn acquire_with_capacity<T>( &mut self, min_capacity: usize ) -> T where T: Poolable {
+        if let Some( (ptr, capacity) ) = self.acquire_local_with_capacity( min_capacity ) {
+            self.hits += 1;
+            return unsafe { T::from_buffer( ptr, capacity ) };
+        }
+
+        self.pull_from_global();
+
+        match self.acquire_local_with_capacity( min_capacity ) {
+            Some( (ptr, capacity) ) => {
+                self.hits += 1;
+                unsafe { T::from_buffer( ptr, capacity ) }
+            },
+            None => {
+                self.misses += 1;
+                T::with_capacity( min_capacity )
+            }
         }
     }
 
@@ -30,7 +273,10 @@ impl MemoryPool {
             let (ptr, capacity) = value.get_buffer();
             if capacity != 0 {
                 mem::forget( value );
-                self.buffers.push( (ptr, capacity) );
+                let bucket = Self::bucket_for( capacity );
+                self.buffers[ bucket ].push( (ptr, capacity) );
+                self.enforce_limits();
+                self.spill_to_global_if_full();
             }
         }
     }
@@ -43,14 +289,27 @@ impl MemoryPool {
 
         result
     }
+
+    #[inline]
+    fn borrow_with_capacity<T, F, R>( &mut self, min_capacity: usize, callback: F ) -> R where F: FnOnce( &mut T ) -> R, T: Poolable {
+        let mut value = self.acquire_with_capacity::<T>( min_capacity );
+        let result = callback( &mut value );
+        self.release::<T>( value );
+
+        result
+    }
 }
 
 impl Drop for MemoryPool {
     fn drop( &mut self ) {
-        for &(ptr, capacity) in self.buffers.iter() {
-            let vector = unsafe { Vec::from_raw_parts( ptr, 0, capacity ) };
-            mem::drop( vector );
-        }
+        // Don't just free our buffers; hand them off to the global pool so other
+        // threads can still recycle them.
+        let batch: Vec< _ > = self.buffers.drain( .. )
+            .flat_map( |bucket| bucket.into_iter() )
+            .map( |(ptr, capacity)| RawBuffer( ptr, capacity ) )
+            .collect();
+
+        GLOBAL.lock().unwrap().push_batch( batch );
     }
 }
 
@@ -75,6 +334,23 @@ pub fn acquire<T>() -> T where T: Poolable {
     result
 }
 
+/// Constructs an object of type `T` with memory from the thread-local pool which is at
+/// least `min_capacity` bytes big, picking the smallest buffer which is big enough instead
+/// of an arbitrary one, and allocating a fresh one of `min_capacity` bytes (rounded up to
+/// a whole number of elements) if none of the pooled buffers qualify.
+pub fn acquire_with_capacity<T>( min_capacity: usize ) -> T where T: Poolable {
+    let mut result = unsafe { mem::uninitialized() };
+    with_pool( |pool| {
+        let mut tmp = pool.acquire_with_capacity::< T >( min_capacity );
+        mem::swap( &mut result, &mut tmp );
+        unsafe {
+            mem::forget( tmp );
+        }
+    });
+
+    result
+}
+
 /// Destroys the `value` and transfers its internal memory buffer back into the thread-local pool.
 pub fn release<T>( value: T ) where T: Poolable {
     with_pool( |pool| {
@@ -92,12 +368,99 @@ pub fn borrow<F, T, R>( callback: F ) -> R where F: FnOnce( &mut T ) -> R, T: Po
     result.unwrap()
 }
 
+/// Like [`borrow`], but uses [`acquire_with_capacity`] to pick the buffer it hands to
+/// the `callback`.
+pub fn borrow_with_capacity<F, T, R>( min_capacity: usize, callback: F ) -> R where F: FnOnce( &mut T ) -> R, T: Poolable {
+    let mut result = None;
+    with_pool( |pool| {
+        result = Some( pool.borrow_with_capacity( min_capacity, callback ) );
+    });
+
+    result.unwrap()
+}
+
+/// Caps how many buffers (and how many total bytes) the thread-local pool is allowed to
+/// retain. Once either limit would be exceeded, `release` frees the smallest retained
+/// buffer instead of keeping it, so the pool doesn't end up pinning large allocations
+/// forever. Pass `usize::max_value()` for a limit to leave it uncapped.
+pub fn set_limits( max_buffers: usize, max_total_bytes: usize ) {
+    with_pool( |pool| {
+        pool.set_limits( max_buffers, max_total_bytes );
+    });
+}
+
+/// Returns a snapshot of the thread-local pool's usage, useful for tuning the limits
+/// passed to [`set_limits`].
+pub fn stats() -> Stats {
+    let mut result = None;
+    with_pool( |pool| {
+        result = Some( pool.stats() );
+    });
+
+    result.unwrap()
+}
+
+/// A RAII guard around a value acquired from the thread-local pool.
+///
+/// Derefs to the underlying `T`, and automatically releases it back into the pool
+/// once dropped, so you don't have to remember to call [`release`] yourself (and
+/// the buffer won't leak even if a panic unwinds through the guard).
+pub struct Reusable< T > where T: Poolable {
+    value: Option< T >
+}
+
+impl< T > Reusable< T > where T: Poolable {
+    /// Extracts the underlying value, preventing it from being automatically
+    /// returned to the pool.
+    pub fn detach( mut self ) -> T {
+        self.value.take().unwrap()
+    }
+}
+
+impl< T > Deref for Reusable< T > where T: Poolable {
+    type Target = T;
+
+    fn deref( &self ) -> &T {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl< T > DerefMut for Reusable< T > where T: Poolable {
+    fn deref_mut( &mut self ) -> &mut T {
+        self.value.as_mut().unwrap()
+    }
+}
+
+impl< T > Drop for Reusable< T > where T: Poolable {
+    fn drop( &mut self ) {
+        if let Some( value ) = self.value.take() {
+            release( value );
+        }
+    }
+}
+
+/// Constructs an object of type `T` with memory from the thread-local pool, wrapped in a
+/// [`Reusable`] guard which will automatically release it back into the pool once dropped.
+pub fn acquire_guard< T >() -> Reusable< T > where T: Poolable {
+    Reusable {
+        value: Some( acquire() )
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     mod memory_pool {
         pub use super::super::*;
     }
 
+    // The global pool (and its `max_buffers` cap) is a single process-wide resource, so
+    // any test which exercises it (directly, or indirectly through `acquire_with_capacity`
+    // falling back to it) needs to run exclusive of the others, or they can steal buffers
+    // from one another.
+    static GLOBAL_POOL_TEST_LOCK: Mutex< () > = Mutex::new( () );
+
     #[test]
     fn borrow_string() {
         memory_pool::borrow( |aux: &mut String| {
@@ -143,4 +506,153 @@ mod tests {
             assert_eq!( vec.capacity(), 21 / 4 );
         });
     }
+
+    #[test]
+    fn plain_acquire_prefers_the_smallest_available_buffer() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        let small: String = memory_pool::acquire_with_capacity( 8 );
+        memory_pool::release( small );
+        let big: String = memory_pool::acquire_with_capacity( 1_000_000 );
+        memory_pool::release( big );
+
+        // A capacity-agnostic `acquire` shouldn't hand back the huge buffer just
+        // because it happens to sit in the last bucket.
+        for _ in 0..2 {
+            let string: String = memory_pool::acquire();
+            assert!( string.capacity() < 1_000_000 );
+            memory_pool::release( string );
+        }
+    }
+
+    #[test]
+    fn acquire_guard_releases_on_drop() {
+        {
+            let mut guard = memory_pool::acquire_guard::< String >();
+            guard.push_str( "I like cupcakes!" );
+        }
+
+        let string: String = memory_pool::acquire();
+        assert_eq!( string.len(), 0 );
+        assert!( string.capacity() >= 16 );
+    }
+
+    #[test]
+    fn acquire_guard_detach() {
+        let mut guard = memory_pool::acquire_guard::< String >();
+        guard.push_str( "I like cupcakes!" );
+        let string = guard.detach();
+        assert_eq!( string, "I like cupcakes!" );
+    }
+
+    #[test]
+    fn acquire_with_capacity_picks_best_fit() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        let small: String = memory_pool::acquire_with_capacity( 16 );
+        memory_pool::release( small );
+        let big: String = memory_pool::acquire_with_capacity( 4096 );
+        memory_pool::release( big );
+
+        // There are now two buffers in the pool; we should get the smaller one back
+        // since it's the tightest fit for this request.
+        let string: String = memory_pool::acquire_with_capacity( 8 );
+        assert!( string.capacity() >= 16 );
+        assert!( string.capacity() < 4096 );
+    }
+
+    #[test]
+    fn acquire_with_capacity_allocates_fresh_when_empty() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        let string: String = memory_pool::acquire_with_capacity( 128 );
+        assert_eq!( string.len(), 0 );
+        assert!( string.capacity() >= 128 );
+    }
+
+    #[test]
+    fn buffers_are_handed_off_through_the_global_pool_across_threads() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        memory_pool::configure_global( 1024 );
+
+        // Use a distinctive, unlikely-to-collide capacity so we can be sure that the
+        // buffer we get back is the very same one the other thread released, and not
+        // just some other buffer of a similar size.
+        let ptr = ::std::thread::spawn( || {
+            let mut buffer: String = memory_pool::acquire_with_capacity( 123456 );
+            buffer.push_str( "x" );
+            let ptr = buffer.as_ptr() as usize;
+            memory_pool::release( buffer );
+            ptr
+            // The thread-local pool is dropped here, spilling its buffers into the
+            // global pool instead of freeing them.
+        }).join().unwrap();
+
+        let buffer: String = memory_pool::acquire_with_capacity( 123456 );
+        assert_eq!( buffer.as_ptr() as usize, ptr );
+    }
+
+    #[test]
+    fn pull_from_global_does_not_hoard_the_whole_pool() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        memory_pool::configure_global( 1024 );
+
+        // A producer which releases (and spills, via its `Drop`) far more buffers than
+        // a single pull batch is allowed to take.
+        let produced = super::GLOBAL_BATCH_SIZE * 3;
+        ::std::thread::spawn( move || {
+            for i in 0..produced {
+                let mut buffer: String = memory_pool::acquire_with_capacity( 300_000 + i );
+                buffer.push_str( "x" );
+                memory_pool::release( buffer );
+            }
+        }).join().unwrap();
+
+        // A single miss should only ever pull at most one batch's worth, leaving the
+        // rest in the global pool for other threads to draw from.
+        let _buffer: String = memory_pool::acquire_with_capacity( 300_000 );
+        let stats = memory_pool::stats();
+        assert!( stats.buffer_count < super::GLOBAL_BATCH_SIZE );
+    }
+
+    #[test]
+    fn set_limits_evicts_down_to_the_cap() {
+        let _guard = GLOBAL_POOL_TEST_LOCK.lock().unwrap();
+
+        memory_pool::set_limits( 1, usize::max_value() );
+
+        let first: String = memory_pool::acquire_with_capacity( 16 );
+        let second: String = memory_pool::acquire_with_capacity( 256 );
+        memory_pool::release( first );
+        memory_pool::release( second );
+
+        // Only the second, bigger buffer should have survived; the smaller one got
+        // evicted (and actually freed) to keep us under the `max_buffers` cap.
+        let stats = memory_pool::stats();
+        assert_eq!( stats.buffer_count, 1 );
+
+        let buffer: String = memory_pool::acquire_with_capacity( 256 );
+        assert!( buffer.capacity() >= 256 );
+
+        memory_pool::set_limits( usize::max_value(), usize::max_value() );
+    }
+
+    #[test]
+    fn stats_track_hits_and_misses() {
+        memory_pool::set_limits( usize::max_value(), usize::max_value() );
+
+        let before = memory_pool::stats();
+
+        let mut buffer: String = memory_pool::acquire();
+        buffer.push_str( "cupcakes" );
+        memory_pool::release( buffer );
+        let buffer: String = memory_pool::acquire();
+        memory_pool::release( buffer );
+
+        let after = memory_pool::stats();
+        assert_eq!( after.misses, before.misses + 1 );
+        assert_eq!( after.hits, before.hits + 1 );
+    }
 }
@@ -9,6 +9,10 @@ pub trait Poolable {
     /// Constructs a fresh, empty `Self`.
     fn empty() -> Self;
 
+    /// Constructs a fresh `Self` freshly allocated with at least the given `capacity`
+    /// (in bytes).
+    fn with_capacity( capacity: usize ) -> Self;
+
     /// Converts given `ptr` into an instance of `Self`. Will only be called with a non-null
     /// `ptr` and non-zero `capacity`.
     unsafe fn from_buffer( ptr: *mut u8, capacity: usize ) -> Self;
@@ -25,6 +29,11 @@ impl<T> Poolable for Vec<T> {
         Vec::new()
     }
 
+    fn with_capacity( capacity: usize ) -> Self {
+        let element_size = mem::size_of::< T >();
+        Vec::with_capacity( (capacity + element_size - 1) / element_size )
+    }
+
     unsafe fn from_buffer( ptr: *mut u8, capacity: usize ) -> Self {
         Vec::from_raw_parts( mem::transmute( ptr ), 0, capacity / mem::size_of::< T >() )
     }
@@ -41,6 +50,10 @@ impl Poolable for String {
         String::new()
     }
 
+    fn with_capacity( capacity: usize ) -> Self {
+        String::with_capacity( capacity )
+    }
+
     unsafe fn from_buffer( ptr: *mut u8, capacity: usize ) -> Self {
         String::from_raw_parts( mem::transmute( ptr ), 0, capacity )
     }
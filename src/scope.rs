@@ -0,0 +1,112 @@
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem;
+
+use super::{acquire, release};
+
+/// An arena for stashing ownership of arbitrary short-lived values of mixed types.
+///
+/// Values passed to [`keep`](Scope::keep) are kept alive for as long as the `Scope`
+/// itself is, and are all dropped at once once the `Scope` goes out of scope, so you
+/// don't have to juggle the lifetimes of many unrelated temporaries by hand. The
+/// `Scope`'s own backing storage is drawn from (and returned to) the thread-local pool.
+pub struct Scope< 'a > {
+    storage: UnsafeCell< Vec< Box< dyn Any > > >,
+    _marker: PhantomData< &'a () >
+}
+
+impl< 'a > Scope< 'a > {
+    /// Creates a new, empty `Scope`.
+    pub fn new() -> Scope< 'a > {
+        Scope {
+            storage: UnsafeCell::new( acquire() ),
+            _marker: PhantomData
+        }
+    }
+
+    /// Moves `value` into the scope and returns a reference to it valid for as long as
+    /// the `Scope` itself is alive.
+    ///
+    /// Takes `&'a self` (rather than just `&self`) so the returned reference can never
+    /// outlive the `Scope` it was handed out by: the borrow checker ties `'a` to an
+    /// actual borrow of `self`, instead of letting the caller pick `'a` (e.g. `'static`)
+    /// independently of how long the `Scope` itself is actually kept around.
+    //
+    // Clippy flags this as `mut_from_ref` since the signature hands out a `&mut T` from
+    // a `&self`, which is a common aliasing footgun. It's sound here: every call to
+    // `keep` allocates its own, disjoint `Box` on the heap, so no two calls ever hand
+    // out overlapping references, and reallocating the `Vec`'s own spine (to fit more
+    // entries) never moves the already-boxed payloads it points into.
+    #[allow(clippy::mut_from_ref)]
+    pub fn keep< T: 'static >( &'a self, value: T ) -> &'a mut T {
+        // Safe, because the only thing we ever do with `storage` is push onto it (which
+        // never invalidates the already-boxed values) and we never hand out more than
+        // one reference to any given element.
+        let storage = unsafe { &mut *self.storage.get() };
+        storage.push( Box::new( value ) );
+        let boxed = storage.last_mut().unwrap();
+        let value_ref = boxed.downcast_mut::< T >().unwrap();
+
+        // Safe, since `value_ref` points into the `Box`'s own heap allocation, which
+        // doesn't move even when `storage` itself reallocates, and which will stay
+        // alive for as long as `self` (and hence this reference) does.
+        unsafe { mem::transmute( value_ref ) }
+    }
+}
+
+impl< 'a > Drop for Scope< 'a > {
+    fn drop( &mut self ) {
+        let storage = unsafe { &mut *self.storage.get() };
+
+        // Drop every value we're holding onto before returning the (now empty) `Vec`'s
+        // spine allocation to the pool, so we don't leak any of them.
+        storage.clear();
+        let storage = mem::replace( storage, Vec::new() );
+        release( storage );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scope;
+
+    #[test]
+    fn keep_multiple_mixed_values() {
+        let scope = Scope::new();
+        let a = scope.keep( 123u32 );
+        let b = scope.keep( String::from( "cupcakes" ) );
+
+        assert_eq!( *a, 123 );
+        assert_eq!( b, "cupcakes" );
+
+        *a += 1;
+        b.push_str( "!" );
+
+        assert_eq!( *a, 124 );
+        assert_eq!( b, "cupcakes!" );
+    }
+
+    #[test]
+    fn drops_kept_values_once_the_scope_ends() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let dropped = Rc::new( Cell::new( false ) );
+
+        struct MarkOnDrop( Rc< Cell< bool > > );
+        impl Drop for MarkOnDrop {
+            fn drop( &mut self ) {
+                self.0.set( true );
+            }
+        }
+
+        {
+            let scope = Scope::new();
+            scope.keep( MarkOnDrop( dropped.clone() ) );
+            assert!( !dropped.get() );
+        }
+
+        assert!( dropped.get() );
+    }
+}